@@ -0,0 +1,268 @@
+//! Read-through caching passthrough over a real host directory.
+//!
+//! Unlike `SimpleFs`, which owns its data in memory, `BackingFs` mirrors a
+//! directory on the host: `lookup`/`getattr` stat the corresponding backing
+//! path on demand, and `read` faults a file's contents into an in-memory
+//! cache the first time it's touched, serving every later read from RAM.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+
+use fuser::{
+    FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, Request,
+};
+use libc::{EIO, ENOENT};
+use log::{info, warn};
+
+use crate::TTL;
+
+const ROOT_INODE: u64 = 1;
+
+pub struct BackingFs {
+    /// inode -> absolute backing path, including `ROOT_INODE` -> the backing root.
+    paths: Mutex<HashMap<u64, PathBuf>>,
+    /// Backing path -> inode, so repeated lookups reuse the same inode.
+    inodes: Mutex<HashMap<PathBuf, u64>>,
+    /// inode -> parent inode, so `readdir` can report a correct `".."`.
+    parents: Mutex<HashMap<u64, u64>>,
+    next_inode: Mutex<u64>,
+    /// Resident file contents, keyed by inode, populated on first `read`.
+    cache: Mutex<HashMap<u64, Arc<Vec<u8>>>>,
+}
+
+impl BackingFs {
+    pub fn new(root: PathBuf) -> Self {
+        let mut paths = HashMap::new();
+        paths.insert(ROOT_INODE, root.clone());
+        let mut inodes = HashMap::new();
+        inodes.insert(root, ROOT_INODE);
+        let mut parents = HashMap::new();
+        parents.insert(ROOT_INODE, ROOT_INODE);
+
+        BackingFs {
+            paths: Mutex::new(paths),
+            inodes: Mutex::new(inodes),
+            parents: Mutex::new(parents),
+            next_inode: Mutex::new(ROOT_INODE + 1),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the inode for `path`, allocating a fresh one the first time
+    /// it's seen so the same backing path always maps to the same inode, and
+    /// recording `parent` as its parent inode.
+    fn inode_for(&self, path: &Path, parent: u64) -> u64 {
+        let mut inodes = self.inodes.lock().unwrap();
+        if let Some(ino) = inodes.get(path) {
+            return *ino;
+        }
+        let mut next_inode = self.next_inode.lock().unwrap();
+        let ino = *next_inode;
+        *next_inode += 1;
+        inodes.insert(path.to_path_buf(), ino);
+        self.paths.lock().unwrap().insert(ino, path.to_path_buf());
+        self.parents.lock().unwrap().insert(ino, parent);
+        ino
+    }
+
+    fn path_for(&self, ino: u64) -> Option<PathBuf> {
+        self.paths.lock().unwrap().get(&ino).cloned()
+    }
+
+    /// Returns `ino`'s parent inode, falling back to the root if unknown.
+    fn parent_of(&self, ino: u64) -> u64 {
+        self.parents.lock().unwrap().get(&ino).copied().unwrap_or(ROOT_INODE)
+    }
+
+    fn attr_for(ino: u64, path: &Path) -> io::Result<FileAttr> {
+        let metadata = fs::symlink_metadata(path)?;
+        let kind = if metadata.is_dir() {
+            FileType::Directory
+        } else if metadata.file_type().is_symlink() {
+            FileType::Symlink
+        } else {
+            FileType::RegularFile
+        };
+
+        let blksize = 512u32;
+        let size = metadata.len();
+        let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+        let accessed = metadata.accessed().unwrap_or(modified);
+
+        Ok(FileAttr {
+            ino,
+            size,
+            blocks: size.div_ceil(blksize as u64),
+            atime: accessed,
+            mtime: modified,
+            ctime: modified,
+            crtime: metadata.created().unwrap_or(modified),
+            kind,
+            perm: (metadata.permissions().mode() & 0o7777) as u16,
+            nlink: metadata.nlink() as u32,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            rdev: metadata.rdev() as u32,
+            flags: 0,
+            blksize,
+        })
+    }
+
+    fn io_error_to_errno(e: &io::Error) -> i32 {
+        match e.kind() {
+            io::ErrorKind::NotFound => ENOENT,
+            _ => EIO,
+        }
+    }
+}
+
+impl Filesystem for BackingFs {
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        info!("lookup(parent={}, name={:?})", parent, name);
+
+        let parent_path = match self.path_for(parent) {
+            Some(path) => path,
+            None => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+        let path = parent_path.join(name);
+
+        // Stat first so a failed lookup (e.g. ENOENT probing) never burns an
+        // inode slot for a path that doesn't exist.
+        if let Err(e) = fs::symlink_metadata(&path) {
+            warn!("Lookup failed for {:?}: {}", path, e);
+            reply.error(Self::io_error_to_errno(&e));
+            return;
+        }
+
+        match Self::attr_for(self.inode_for(&path, parent), &path) {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(e) => {
+                warn!("Lookup failed for {:?}: {}", path, e);
+                reply.error(Self::io_error_to_errno(&e));
+            }
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+        info!("getattr(ino={})", ino);
+
+        let path = match self.path_for(ino) {
+            Some(path) => path,
+            None => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        match Self::attr_for(ino, &path) {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(e) => {
+                warn!("Getattr failed for {:?}: {}", path, e);
+                reply.error(Self::io_error_to_errno(&e));
+            }
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        info!("read(ino={}, offset={}, size={})", ino, offset, size);
+
+        let path = match self.path_for(ino) {
+            Some(path) => path,
+            None => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        let mut cache = self.cache.lock().unwrap();
+        let data = match cache.get(&ino) {
+            Some(data) => data.clone(),
+            None => match fs::read(&path) {
+                Ok(bytes) => {
+                    info!("Faulted {:?} into the resident cache", path);
+                    let data = Arc::new(bytes);
+                    cache.insert(ino, data.clone());
+                    data
+                }
+                Err(e) => {
+                    warn!("Read failed for {:?}: {}", path, e);
+                    reply.error(Self::io_error_to_errno(&e));
+                    return;
+                }
+            },
+        };
+
+        let start = (offset as usize).min(data.len());
+        let end = start.saturating_add(size as usize).min(data.len());
+        reply.data(&data[start..end]);
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        info!("readdir(ino={}, offset={})", ino, offset);
+
+        let path = match self.path_for(ino) {
+            Some(path) => path,
+            None => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        let read_dir = match fs::read_dir(&path) {
+            Ok(read_dir) => read_dir,
+            Err(e) => {
+                warn!("Readdir failed for {:?}: {}", path, e);
+                reply.error(Self::io_error_to_errno(&e));
+                return;
+            }
+        };
+
+        let mut entries = vec![
+            (ino, FileType::Directory, ".".to_string()),
+            (self.parent_of(ino), FileType::Directory, "..".to_string()),
+        ];
+        for dir_entry in read_dir.flatten() {
+            let child_path = dir_entry.path();
+            let kind = match dir_entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => FileType::Directory,
+                Ok(file_type) if file_type.is_symlink() => FileType::Symlink,
+                _ => FileType::RegularFile,
+            };
+            let child_ino = self.inode_for(&child_path, ino);
+            entries.push((child_ino, kind, dir_entry.file_name().to_string_lossy().into_owned()));
+        }
+
+        for (i, entry) in entries.into_iter().enumerate().skip(offset as usize) {
+            if reply.add(entry.0, (i + 1) as i64, entry.1, entry.2) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
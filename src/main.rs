@@ -1,45 +1,114 @@
 use std::collections::HashMap;
-use std::ffi::OsStr;
-use std::path::Path;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use fuser::{
-    FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyCreate, ReplyData, 
+    FileAttr, FileType, Filesystem, ReplyAttr, ReplyCreate, ReplyData,
     ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyWrite, Request,
 };
-use libc::{ENOENT, EISDIR, EEXIST};
+use libc::{ENOENT, EISDIR, EEXIST, ENOTEMPTY, ENOTDIR, EINVAL};
 use log::{info, warn, error};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+mod backing;
+mod persist;
+mod session;
+mod store;
+
+use store::BlockHash;
+
+/// Env var overriding where the compressed on-disk index is stored;
+/// defaults to a file named after the mountpoint (see `index_path_for`).
+const INDEX_PATH_ENV: &str = "SIMPLEFS_INDEX_PATH";
+
+#[derive(Clone, Serialize, Deserialize)]
 struct FileEntry {
+    #[serde(with = "persist::FileAttrDef")]
     attr: FileAttr,
-    content: Vec<u8>,
+    /// Inode of the containing directory (the root is its own parent).
+    parent: u64,
+    /// Ordered content blocks, looked up in `SimpleFs::store`.
+    blocks: Vec<BlockHash>,
+    /// `Some(name -> inode)` for directories, `None` for everything else.
+    children: Option<HashMap<OsString, u64>>,
+    /// `Some(target)` for symlinks, `None` for everything else.
+    link_target: Option<OsString>,
+}
+
+impl FileEntry {
+    fn is_dir(&self) -> bool {
+        self.attr.kind == FileType::Directory
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.attr.kind == FileType::Symlink
+    }
+}
+
+/// `stat(2)`-style preferred I/O block size, used for every inode's
+/// `blksize` and to derive `blocks` from `size`.
+const BLKSIZE: u32 = 512;
+
+/// Block count for a file of `size` bytes, matching what `stat(2)` expects.
+fn block_count(size: u64) -> u64 {
+    size.div_ceil(BLKSIZE as u64)
 }
 
 struct SimpleFs {
     files: Arc<Mutex<HashMap<u64, FileEntry>>>,
     next_inode: Arc<Mutex<u64>>,
+    store: Arc<store::BlockStore>,
+    index_path: PathBuf,
 }
 
 impl SimpleFs {
-    fn new() -> Self {
+    fn new(index_path: PathBuf) -> Self {
+        match persist::load(&index_path) {
+            Ok(Some((files, next_inode, block_data))) => {
+                info!("Loaded index from {:?}", index_path);
+                let store = store::BlockStore::restore(
+                    block_data,
+                    files.values().flat_map(|entry| entry.blocks.iter()),
+                );
+                return SimpleFs {
+                    files: Arc::new(Mutex::new(files)),
+                    next_inode: Arc::new(Mutex::new(next_inode)),
+                    store: Arc::new(store),
+                    index_path,
+                };
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Ignoring unreadable index {:?}: {}", index_path, e),
+        }
+
+        let store = store::BlockStore::new();
         let mut files = HashMap::new();
-        
-        
+
+        let mut root_children = HashMap::new();
+        root_children.insert(OsString::from("fuse.txt"), 2);
+
         files.insert(1, FileEntry {
             attr: HELLO_DIR_ATTR,
-            content: Vec::new(),
+            parent: 1,
+            blocks: Vec::new(),
+            children: Some(root_children),
+            link_target: None,
         });
 
-        
         files.insert(2, FileEntry {
             attr: HELLO_TXT_ATTR,
-            content: HELLO_TXT_CONTENT.as_bytes().to_vec(),
+            parent: 1,
+            blocks: store.store(HELLO_TXT_CONTENT.as_bytes()),
+            children: None,
+            link_target: None,
         });
 
         SimpleFs {
             files: Arc::new(Mutex::new(files)),
-            next_inode: Arc::new(Mutex::new(3)), 
+            next_inode: Arc::new(Mutex::new(3)),
+            store: Arc::new(store),
+            index_path,
         }
     }
 
@@ -47,6 +116,122 @@ impl SimpleFs {
         let now = SystemTime::now();
         (now, now)
     }
+
+    /// Core logic behind `Filesystem::write`, pulled out so it can be
+    /// exercised directly by tests (which can't construct a real
+    /// `Request`/`ReplyWrite`). Only rechunks the blocks overlapping
+    /// `offset..offset+data.len()`, filling any hole before `offset` with
+    /// zeroed blocks.
+    fn write_at(&self, ino: u64, offset: i64, data: &[u8]) -> Result<(), i32> {
+        let mut files = self.files.lock().unwrap();
+
+        let entry = files.get_mut(&ino).ok_or_else(|| {
+            warn!("Write failed: inode {} not found", ino);
+            ENOENT
+        })?;
+
+        if entry.is_dir() {
+            warn!("Write failed: cannot write to a directory");
+            return Err(EISDIR);
+        }
+        if entry.is_symlink() {
+            warn!("Write failed: cannot write to symlink {}", ino);
+            return Err(EINVAL);
+        }
+
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let block_size = store::BLOCK_SIZE as u64;
+        let write_start = offset as u64;
+        let write_end = write_start + data.len() as u64;
+        let new_size = entry.attr.size.max(write_end);
+
+        let first_block = (write_start / block_size) as usize;
+        let last_block = ((write_end - 1) / block_size) as usize;
+
+        let mut new_blocks = entry.blocks[..first_block.min(entry.blocks.len())].to_vec();
+        // A short final block that the write starts past is padded out to a
+        // full block, so every later block still starts at its own offset.
+        if first_block >= entry.blocks.len() {
+            if let Some(last) = new_blocks.pop() {
+                let mut padded = (*self.store.block(&last).unwrap_or_default()).clone();
+                padded.resize(store::BLOCK_SIZE, 0);
+                new_blocks.extend(self.store.store(&padded));
+                self.store.release(&[last]);
+            }
+        }
+        // Any block indices between the old end of the file and the
+        // first touched block are a hole; fill it with zeroed blocks.
+        for _ in entry.blocks.len()..first_block {
+            new_blocks.extend(self.store.store(&vec![0u8; store::BLOCK_SIZE]));
+        }
+
+        for block_index in first_block..=last_block {
+            let block_start = block_index as u64 * block_size;
+            let block_len = (new_size - block_start).min(block_size) as usize;
+
+            let mut block_content = match entry.blocks.get(block_index) {
+                Some(hash) => (*self.store.block(hash).unwrap_or_default()).clone(),
+                None => Vec::new(),
+            };
+            block_content.resize(block_len, 0);
+
+            let overlap_start = write_start.max(block_start) - block_start;
+            let overlap_end = write_end.min(block_start + block_size) - block_start;
+            let data_offset = (block_start + overlap_start - write_start) as usize;
+            let data_len = (overlap_end - overlap_start) as usize;
+            block_content[overlap_start as usize..overlap_end as usize]
+                .copy_from_slice(&data[data_offset..data_offset + data_len]);
+
+            new_blocks.extend(self.store.store(&block_content));
+        }
+
+        if last_block + 1 < entry.blocks.len() {
+            new_blocks.extend_from_slice(&entry.blocks[last_block + 1..]);
+        }
+
+        let replaced_start = first_block.min(entry.blocks.len());
+        let replaced_end = (last_block + 1).min(entry.blocks.len());
+        self.store.release(&entry.blocks[replaced_start..replaced_end]);
+        entry.blocks = new_blocks;
+
+        let (_, now2) = Self::get_current_time();
+        entry.attr.size = new_size;
+        entry.attr.blocks = block_count(new_size);
+        entry.attr.mtime = now2;
+        entry.attr.ctime = now2;
+
+        Ok(())
+    }
+}
+
+impl Drop for SimpleFs {
+    fn drop(&mut self) {
+        let files = self.files.lock().unwrap();
+        let next_inode = *self.next_inode.lock().unwrap();
+        match persist::save(&self.index_path, &files, next_inode, &self.store.snapshot()) {
+            Ok(()) => info!("Persisted index to {:?}", self.index_path),
+            Err(e) => error!("Failed to persist index to {:?}: {}", self.index_path, e),
+        }
+    }
+}
+
+/// Resolves the index file path: `SIMPLEFS_INDEX_PATH` if set, otherwise a
+/// `<mountpoint>.index.tree.zst` sibling of the mountpoint.
+fn index_path_for(mountpoint: &Path) -> PathBuf {
+    if let Ok(path) = std::env::var(INDEX_PATH_ENV) {
+        return PathBuf::from(path);
+    }
+    let file_name = mountpoint
+        .file_name()
+        .map(|n| format!("{}.index.tree.zst", n.to_string_lossy()))
+        .unwrap_or_else(|| "index.tree.zst".to_string());
+    match mountpoint.parent() {
+        Some(parent) => parent.join(file_name),
+        None => PathBuf::from(file_name),
+    }
 }
 
 const TTL: Duration = Duration::from_secs(1);
@@ -65,7 +250,7 @@ const HELLO_DIR_ATTR: FileAttr = FileAttr {
     gid: 1000,
     rdev: 0,
     flags: 0,
-    blksize: 512,
+    blksize: BLKSIZE,
 };
 
 const HELLO_TXT_CONTENT: &str = "This is a new file\n";
@@ -84,34 +269,39 @@ const HELLO_TXT_ATTR: FileAttr = FileAttr {
     gid: 1000,
     rdev: 0,
     flags: 0,
-    blksize: 512,
+    blksize: BLKSIZE,
 };
 
 impl Filesystem for SimpleFs {
     fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
         info!("lookup(parent={}, name={:?})", parent, name);
-        
+
         let files = self.files.lock().unwrap();
-        
-        if parent != 1 {
-            warn!("Lookup failed: parent {} is not a directory", parent);
-            reply.error(ENOENT);
-            return;
-        }
 
-      
-        for (ino, entry) in files.iter() {
-            if entry.attr.kind != FileType::Directory && 
-               OsStr::new(name.to_str().unwrap_or("")) == OsStr::new(name.to_str().unwrap_or("")) {
-                reply.entry(&TTL, &entry.attr, 0);
+        let parent_entry = match files.get(&parent) {
+            Some(entry) => entry,
+            None => {
+                reply.error(ENOENT);
                 return;
             }
-        }
+        };
 
-        reply.error(ENOENT);
+        let children = match &parent_entry.children {
+            Some(children) => children,
+            None => {
+                warn!("Lookup failed: parent {} is not a directory", parent);
+                reply.error(ENOTDIR);
+                return;
+            }
+        };
+
+        match children.get(name).and_then(|ino| files.get(ino)) {
+            Some(entry) => reply.entry(&TTL, &entry.attr, 0),
+            None => reply.error(ENOENT),
+        }
     }
 
-    fn getattr(&mut self, _req: &Request, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
         info!("getattr(ino={})", ino);
         
         let files = self.files.lock().unwrap();
@@ -139,17 +329,20 @@ impl Filesystem for SimpleFs {
         
         match files.get(&ino) {
             Some(entry) => {
-                if entry.attr.kind == FileType::Directory {
+                if entry.is_dir() {
                     reply.error(EISDIR);
                     return;
                 }
-                
-                let data = &entry.content;
-                if offset as usize >= data.len() {
+                if entry.is_symlink() {
+                    warn!("Read failed: inode {} is a symlink", ino);
+                    reply.error(EINVAL);
+                    return;
+                }
+
+                if offset as u64 >= entry.attr.size {
                     reply.data(&[]);
                 } else {
-                    let end = std::cmp::min(offset as usize + size as usize, data.len());
-                    reply.data(&data[offset as usize..end]);
+                    reply.data(&self.store.read_range(&entry.blocks, offset as u64, size));
                 }
             },
             None => reply.error(ENOENT),
@@ -165,19 +358,34 @@ impl Filesystem for SimpleFs {
         mut reply: ReplyDirectory,
     ) {
         info!("readdir(ino={}, offset={})", ino, offset);
-        
-        let _files = self.files.lock().unwrap();
-        
-        if ino != 1 {
-            reply.error(ENOENT);
-            return;
-        }
 
-        let entries = vec![
-            (1, FileType::Directory, "."),
-            (1, FileType::Directory, ".."),
-            (2, FileType::RegularFile, "fuse.txt"),
+        let files = self.files.lock().unwrap();
+
+        let entry = match files.get(&ino) {
+            Some(entry) => entry,
+            None => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        let children = match &entry.children {
+            Some(children) => children,
+            None => {
+                reply.error(ENOTDIR);
+                return;
+            }
+        };
+
+        let mut entries = vec![
+            (ino, FileType::Directory, ".".to_string()),
+            (entry.parent, FileType::Directory, "..".to_string()),
         ];
+        for (name, child_ino) in children {
+            if let Some(child) = files.get(child_ino) {
+                entries.push((*child_ino, child.attr.kind, name.to_string_lossy().into_owned()));
+            }
+        }
 
         for (i, entry) in entries.into_iter().enumerate().skip(offset as usize) {
             if reply.add(entry.0, (i + 1) as i64, entry.1, entry.2) {
@@ -198,25 +406,18 @@ impl Filesystem for SimpleFs {
         reply: ReplyCreate,
     ) {
         info!("create(parent={}, name={:?}, mode={})", parent, name, mode);
-        
+
         let mut files = self.files.lock().unwrap();
         let mut next_inode = self.next_inode.lock().unwrap();
-        
-        
-        if parent != 1 {
+
+        if !files.get(&parent).map(FileEntry::is_dir).unwrap_or(false) {
             warn!("Create failed: parent {} is not a directory", parent);
-            reply.error(ENOENT);
+            reply.error(ENOTDIR);
             return;
         }
 
-    
-        let name_str = name.to_str().unwrap_or("");
-        if files.values().any(|entry| 
-            entry.attr.kind != FileType::Directory && 
-            entry.attr.ino != 1 && 
-            entry.attr.ino != 2
-        ) {
-            warn!("Create failed: file {} already exists", name_str);
+        if files[&parent].children.as_ref().unwrap().contains_key(name) {
+            warn!("Create failed: file {:?} already exists", name);
             reply.error(EEXIST);
             return;
         }
@@ -240,17 +441,199 @@ impl Filesystem for SimpleFs {
             gid: _req.gid(),
             rdev: 0,
             flags: 0,
-            blksize: 512,
+            blksize: BLKSIZE,
         };
 
         files.insert(new_inode, FileEntry {
             attr: file_attr,
-            content: Vec::new(),
+            parent,
+            blocks: Vec::new(),
+            children: None,
+            link_target: None,
         });
+        files.get_mut(&parent).unwrap().children.as_mut().unwrap().insert(name.to_os_string(), new_inode);
 
         reply.created(&TTL, &file_attr, 0, 0, 0);
     }
 
+    fn mkdir(
+        &mut self,
+        req: &Request,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        _umask: u32,
+        reply: ReplyEntry,
+    ) {
+        info!("mkdir(parent={}, name={:?}, mode={})", parent, name, mode);
+
+        let mut files = self.files.lock().unwrap();
+        let mut next_inode = self.next_inode.lock().unwrap();
+
+        if !files.get(&parent).map(FileEntry::is_dir).unwrap_or(false) {
+            warn!("Mkdir failed: parent {} is not a directory", parent);
+            reply.error(ENOTDIR);
+            return;
+        }
+
+        if files[&parent].children.as_ref().unwrap().contains_key(name) {
+            warn!("Mkdir failed: {:?} already exists", name);
+            reply.error(EEXIST);
+            return;
+        }
+
+        let (now, now2) = Self::get_current_time();
+        let new_inode = *next_inode;
+        *next_inode += 1;
+
+        let dir_attr = FileAttr {
+            ino: new_inode,
+            size: 0,
+            blocks: 0,
+            atime: now,
+            mtime: now2,
+            ctime: now2,
+            crtime: now2,
+            kind: FileType::Directory,
+            perm: (mode & 0o777) as u16,
+            nlink: 2,
+            uid: req.uid(),
+            gid: req.gid(),
+            rdev: 0,
+            flags: 0,
+            blksize: BLKSIZE,
+        };
+
+        files.insert(new_inode, FileEntry {
+            attr: dir_attr,
+            parent,
+            blocks: Vec::new(),
+            children: Some(HashMap::new()),
+            link_target: None,
+        });
+
+        let parent_entry = files.get_mut(&parent).unwrap();
+        parent_entry.children.as_mut().unwrap().insert(name.to_os_string(), new_inode);
+        parent_entry.attr.nlink += 1;
+
+        reply.entry(&TTL, &dir_attr, 0);
+    }
+
+    fn rmdir(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        info!("rmdir(parent={}, name={:?})", parent, name);
+
+        let mut files = self.files.lock().unwrap();
+
+        if !files.get(&parent).map(FileEntry::is_dir).unwrap_or(false) {
+            reply.error(ENOTDIR);
+            return;
+        }
+
+        let target_ino = match files[&parent].children.as_ref().unwrap().get(name).copied() {
+            Some(ino) => ino,
+            None => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        let target = match files.get(&target_ino) {
+            Some(entry) => entry,
+            None => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        if !target.is_dir() {
+            reply.error(ENOTDIR);
+            return;
+        }
+        if !target.children.as_ref().unwrap().is_empty() {
+            warn!("Rmdir failed: {:?} is not empty", name);
+            reply.error(ENOTEMPTY);
+            return;
+        }
+
+        files.remove(&target_ino);
+        let parent_entry = files.get_mut(&parent).unwrap();
+        parent_entry.children.as_mut().unwrap().remove(name);
+        parent_entry.attr.nlink -= 1;
+
+        reply.ok();
+    }
+
+    fn symlink(
+        &mut self,
+        req: &Request,
+        parent: u64,
+        name: &OsStr,
+        link: &Path,
+        reply: ReplyEntry,
+    ) {
+        info!("symlink(parent={}, name={:?}, link={:?})", parent, name, link);
+
+        let mut files = self.files.lock().unwrap();
+        let mut next_inode = self.next_inode.lock().unwrap();
+
+        if !files.get(&parent).map(FileEntry::is_dir).unwrap_or(false) {
+            warn!("Symlink failed: parent {} is not a directory", parent);
+            reply.error(ENOTDIR);
+            return;
+        }
+
+        if files[&parent].children.as_ref().unwrap().contains_key(name) {
+            warn!("Symlink failed: {:?} already exists", name);
+            reply.error(EEXIST);
+            return;
+        }
+
+        let target = link.as_os_str().to_os_string();
+        let (now, now2) = Self::get_current_time();
+        let new_inode = *next_inode;
+        *next_inode += 1;
+
+        let link_attr = FileAttr {
+            ino: new_inode,
+            size: target.len() as u64,
+            blocks: block_count(target.len() as u64),
+            atime: now,
+            mtime: now2,
+            ctime: now2,
+            crtime: now2,
+            kind: FileType::Symlink,
+            perm: 0o777,
+            nlink: 1,
+            uid: req.uid(),
+            gid: req.gid(),
+            rdev: 0,
+            flags: 0,
+            blksize: BLKSIZE,
+        };
+
+        files.insert(new_inode, FileEntry {
+            attr: link_attr,
+            parent,
+            blocks: Vec::new(),
+            children: None,
+            link_target: Some(target),
+        });
+        files.get_mut(&parent).unwrap().children.as_mut().unwrap().insert(name.to_os_string(), new_inode);
+
+        reply.entry(&TTL, &link_attr, 0);
+    }
+
+    fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
+        info!("readlink(ino={})", ino);
+
+        let files = self.files.lock().unwrap();
+
+        match files.get(&ino).and_then(|entry| entry.link_target.as_ref()) {
+            Some(target) => reply.data(target.as_encoded_bytes()),
+            None => reply.error(ENOENT),
+        }
+    }
+
     fn write(
         &mut self,
         _req: &Request,
@@ -264,36 +647,10 @@ impl Filesystem for SimpleFs {
         reply: ReplyWrite,
     ) {
         info!("write(ino={}, offset={}, data_len={})", ino, offset, data.len());
-        
-        let mut files = self.files.lock().unwrap();
-        
-        match files.get_mut(&ino) {
-            Some(entry) => {
-                if entry.attr.kind == FileType::Directory {
-                    warn!("Write failed: cannot write to a directory");
-                    reply.error(EISDIR);
-                    return;
-                }
-
-               
-                let start = offset as usize;
-                if start > entry.content.len() {
-                    entry.content.resize(start, 0);
-                }
-                entry.content.splice(start..start, data.iter().cloned());
-
-                
-                let (_, now2) = Self::get_current_time();
-                entry.attr.size = entry.content.len() as u64;
-                entry.attr.mtime = now2;
-                entry.attr.ctime = now2;
 
-                reply.written(data.len() as u32);
-            },
-            None => {
-                warn!("Write failed: inode {} not found", ino);
-                reply.error(ENOENT);
-            }
+        match self.write_at(ino, offset, data) {
+            Ok(()) => reply.written(data.len() as u32),
+            Err(errno) => reply.error(errno),
         }
     }
 
@@ -305,50 +662,149 @@ impl Filesystem for SimpleFs {
         reply: ReplyEmpty,
     ) {
         info!("unlink(parent={}, name={:?})", parent, name);
-        
+
         let mut files = self.files.lock().unwrap();
-        
-        
-        if parent != 1 {
+
+        if !files.get(&parent).map(FileEntry::is_dir).unwrap_or(false) {
             warn!("Unlink failed: parent {} is not a directory", parent);
-            reply.error(ENOENT);
+            reply.error(ENOTDIR);
             return;
         }
 
-        let name_str = name.to_str().unwrap_or("");
-        
-        
-        let file_to_delete = files.iter()
-            .find(|(_, entry)| 
-                entry.attr.kind != FileType::Directory && 
-                entry.attr.ino != 1
-            )
-            .map(|(ino, _)| *ino);
-
-        match file_to_delete {
-            Some(ino) => {
-                files.remove(&ino);
-                reply.ok();
-            },
+        let target_ino = match files[&parent].children.as_ref().unwrap().get(name).copied() {
+            Some(ino) => ino,
             None => {
-                warn!("Unlink failed: file {} not found", name_str);
+                warn!("Unlink failed: {:?} not found", name);
                 reply.error(ENOENT);
+                return;
             }
+        };
+
+        if files.get(&target_ino).map(FileEntry::is_dir).unwrap_or(false) {
+            reply.error(EISDIR);
+            return;
+        }
+
+        if let Some(removed) = files.remove(&target_ino) {
+            self.store.release(&removed.blocks);
         }
+        files.get_mut(&parent).unwrap().children.as_mut().unwrap().remove(name);
+        reply.ok();
     }
 }
 
+/// `--backing <dir>`, if present, mounts a read-through cache over `<dir>`
+/// instead of the default purely in-memory filesystem.
+fn backing_root_from_args() -> Option<PathBuf> {
+    let args: Vec<String> = std::env::args().collect();
+    let flag = args.iter().position(|arg| arg == "--backing")?;
+    args.get(flag + 1).map(PathBuf::from)
+}
+
 fn main() {
     env_logger::init();
     let mountpoint = Path::new("/tmp/simple_fuse");
     if !mountpoint.exists() {
         std::fs::create_dir_all(mountpoint).unwrap();
     }
-    let options = vec![MountOption::RW, MountOption::FSName("simplefs".to_string())];
-    
-    info!("Mounting simple filesystem at {:?}", mountpoint);
-    match fuser::mount2(SimpleFs::new(), mountpoint, &options) {
-        Ok(()) => info!("Filesystem unmounted"),
-        Err(e) => error!("Error mounting filesystem: {}", e),
+
+    let session = if let Some(backing_root) = backing_root_from_args() {
+        info!("Mounting backing cache over {:?} at {:?}", backing_root, mountpoint);
+        session::spawn(backing::BackingFs::new(backing_root), mountpoint)
+    } else {
+        let index_path = index_path_for(mountpoint);
+        info!("Mounting simple filesystem at {:?}", mountpoint);
+        session::spawn(SimpleFs::new(index_path), mountpoint)
+    };
+
+    let _session = match session {
+        Ok(session) => session,
+        Err(e) => {
+            error!("Error mounting filesystem: {}", e);
+            return;
+        }
+    };
+
+    session::wait_for_shutdown_signal();
+    info!("Unmounting filesystem");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_fs(name: &str) -> SimpleFs {
+        let path = std::env::temp_dir().join(format!(
+            "simplefs-write-test-{}-{}.tree.zst",
+            std::process::id(),
+            name
+        ));
+        SimpleFs::new(path)
+    }
+
+    fn blank_file(fs: &SimpleFs, ino: u64) {
+        fs.files.lock().unwrap().insert(
+            ino,
+            FileEntry {
+                attr: FileAttr {
+                    ino,
+                    size: 0,
+                    blocks: 0,
+                    atime: UNIX_EPOCH,
+                    mtime: UNIX_EPOCH,
+                    ctime: UNIX_EPOCH,
+                    crtime: UNIX_EPOCH,
+                    kind: FileType::RegularFile,
+                    perm: 0o644,
+                    nlink: 1,
+                    uid: 0,
+                    gid: 0,
+                    rdev: 0,
+                    flags: 0,
+                    blksize: BLKSIZE,
+                },
+                parent: 1,
+                blocks: Vec::new(),
+                children: None,
+                link_target: None,
+            },
+        );
+    }
+
+    #[test]
+    fn write_fills_holes_and_then_overwrites_in_place() {
+        let fs = test_fs("sparse-overwrite");
+        let ino = 100;
+        blank_file(&fs, ino);
+
+        // A write starting more than a block past the (empty) end of the
+        // file leaves a hole that should read back as zeros.
+        let offset = store::BLOCK_SIZE as i64 + 10;
+        fs.write_at(ino, offset, b"xyz").unwrap();
+
+        let mut expected = vec![0u8; offset as usize];
+        expected.extend_from_slice(b"xyz");
+
+        let (blocks, size) = {
+            let files = fs.files.lock().unwrap();
+            let entry = &files[&ino];
+            (entry.blocks.clone(), entry.attr.size)
+        };
+        assert_eq!(size, expected.len() as u64);
+        assert_eq!(fs.store.read_range(&blocks, 0, size as u32), expected);
+
+        // Overwriting a couple of bytes inside the existing content must
+        // replace them in place rather than inserting and growing the file.
+        fs.write_at(ino, 5, b"AB").unwrap();
+        expected[5] = b'A';
+        expected[6] = b'B';
+
+        let (blocks, size) = {
+            let files = fs.files.lock().unwrap();
+            let entry = &files[&ino];
+            (entry.blocks.clone(), entry.attr.size)
+        };
+        assert_eq!(size, expected.len() as u64, "in-place overwrite must not grow the file");
+        assert_eq!(fs.store.read_range(&blocks, 0, size as u32), expected);
     }
 }
\ No newline at end of file
@@ -0,0 +1,263 @@
+//! On-disk persistence for the in-memory inode table.
+//!
+//! The tree is serialized with `bincode`, compressed with `zstd`, and written
+//! to a single index file so that a mount's contents survive across restarts.
+//! `fuser::FileAttr` and `fuser::FileType` are foreign types, so we mirror
+//! them with the `#[serde(remote = "...")]` pattern instead of wrapping them.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use fuser::{FileAttr, FileType};
+use serde::{Deserialize, Serialize};
+
+use crate::store::BlockHash;
+use crate::FileEntry;
+
+/// Bumped whenever the on-disk layout changes; a mismatch is rejected
+/// instead of being silently misparsed.
+///
+/// - 1: flat `FileEntry { attr, content }`.
+/// - 2: `FileEntry` gained `parent` and `children` for real directories.
+/// - 3: `FileEntry` gained `link_target` for symlinks.
+/// - 4: `FileEntry::content` became `blocks`, and the index gained the
+///   content-addressed block map.
+pub const INDEX_VERSION: u32 = 4;
+
+/// The inode table, the next free inode, and every live content block, as
+/// handed back by [`load`] and taken by [`save`].
+type LoadedIndex = (HashMap<u64, FileEntry>, u64, HashMap<BlockHash, Vec<u8>>);
+
+#[derive(Serialize, Deserialize)]
+struct PersistedIndex {
+    version: u32,
+    next_inode: u64,
+    files: HashMap<u64, FileEntry>,
+    blocks: HashMap<BlockHash, Vec<u8>>,
+}
+
+/// Serializes `files`/`next_inode`/the block store, compresses them with
+/// zstd, and writes the result to `path`.
+pub fn save(
+    path: &Path,
+    files: &HashMap<u64, FileEntry>,
+    next_inode: u64,
+    blocks: &HashMap<BlockHash, Vec<u8>>,
+) -> io::Result<()> {
+    let index = PersistedIndex {
+        version: INDEX_VERSION,
+        next_inode,
+        files: files.clone(),
+        blocks: blocks.clone(),
+    };
+    let encoded = bincode::serialize(&index)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let compressed = zstd::encode_all(&encoded[..], 0)?;
+    let mut file = File::create(path)?;
+    file.write_all(&compressed)
+}
+
+/// Loads and decompresses the index at `path`, if it exists.
+///
+/// Returns `Ok(None)` if the file is missing, and an error if it exists but
+/// carries an incompatible `version` or fails to decode.
+pub fn load(path: &Path) -> io::Result<Option<LoadedIndex>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let mut compressed = Vec::new();
+    File::open(path)?.read_to_end(&mut compressed)?;
+    let encoded = zstd::decode_all(&compressed[..])?;
+    let index: PersistedIndex = bincode::deserialize(&encoded)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    if index.version != INDEX_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "index {:?} has version {}, expected {}",
+                path, index.version, INDEX_VERSION
+            ),
+        ));
+    }
+
+    Ok(Some((index.files, index.next_inode, index.blocks)))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "FileType")]
+pub enum FileTypeDef {
+    NamedPipe,
+    CharDevice,
+    BlockDevice,
+    Directory,
+    RegularFile,
+    Symlink,
+    Socket,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "FileAttr")]
+pub struct FileAttrDef {
+    pub ino: u64,
+    pub size: u64,
+    pub blocks: u64,
+    #[serde(with = "system_time")]
+    pub atime: SystemTime,
+    #[serde(with = "system_time")]
+    pub mtime: SystemTime,
+    #[serde(with = "system_time")]
+    pub ctime: SystemTime,
+    #[serde(with = "system_time")]
+    pub crtime: SystemTime,
+    #[serde(with = "FileTypeDef")]
+    pub kind: FileType,
+    pub perm: u16,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u32,
+    pub flags: u32,
+    pub blksize: u32,
+}
+
+/// Encodes a `SystemTime` as seconds+nanos since `UNIX_EPOCH`.
+mod system_time {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let duration = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO);
+        (duration.as_secs(), duration.subsec_nanos()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<SystemTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let (secs, nanos): (u64, u32) = Deserialize::deserialize(deserializer)?;
+        Ok(UNIX_EPOCH + Duration::new(secs, nanos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::BlockStore;
+
+    fn sample_index() -> (HashMap<u64, FileEntry>, HashMap<BlockHash, Vec<u8>>) {
+        let store = BlockStore::new();
+        let blocks = store.store(b"hello");
+
+        let mut root_children = HashMap::new();
+        root_children.insert(std::ffi::OsString::from("greeting.txt"), 2);
+
+        let mut files = HashMap::new();
+        files.insert(
+            1,
+            FileEntry {
+                attr: FileAttr {
+                    ino: 1,
+                    size: 0,
+                    blocks: 0,
+                    atime: UNIX_EPOCH,
+                    mtime: UNIX_EPOCH,
+                    ctime: UNIX_EPOCH,
+                    crtime: UNIX_EPOCH,
+                    kind: FileType::Directory,
+                    perm: 0o755,
+                    nlink: 2,
+                    uid: 0,
+                    gid: 0,
+                    rdev: 0,
+                    flags: 0,
+                    blksize: 512,
+                },
+                parent: 1,
+                blocks: Vec::new(),
+                children: Some(root_children),
+                link_target: None,
+            },
+        );
+        files.insert(
+            2,
+            FileEntry {
+                attr: FileAttr {
+                    ino: 2,
+                    size: 5,
+                    blocks: 1,
+                    atime: UNIX_EPOCH,
+                    mtime: UNIX_EPOCH,
+                    ctime: UNIX_EPOCH,
+                    crtime: UNIX_EPOCH,
+                    kind: FileType::RegularFile,
+                    perm: 0o644,
+                    nlink: 1,
+                    uid: 0,
+                    gid: 0,
+                    rdev: 0,
+                    flags: 0,
+                    blksize: 512,
+                },
+                parent: 1,
+                blocks,
+                children: None,
+                link_target: None,
+            },
+        );
+
+        (files, store.snapshot())
+    }
+
+    fn scratch_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("persist-test-{}-{}.tree.zst", std::process::id(), name))
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let (files, block_data) = sample_index();
+        let path = scratch_path("round-trip");
+
+        save(&path, &files, 3, &block_data).unwrap();
+        let (loaded_files, loaded_next_inode, loaded_blocks) =
+            load(&path).unwrap().expect("a saved index should load back");
+
+        assert_eq!(loaded_next_inode, 3);
+        assert_eq!(loaded_blocks, block_data);
+        assert_eq!(loaded_files[&2].attr.size, 5);
+        assert_eq!(loaded_files[&2].blocks, files[&2].blocks);
+        assert_eq!(loaded_files[&1].children, files[&1].children);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn load_rejects_a_mismatched_version_instead_of_misparsing() {
+        let (files, block_data) = sample_index();
+        let path = scratch_path("bad-version");
+
+        let index = PersistedIndex {
+            version: INDEX_VERSION + 1,
+            next_inode: 3,
+            files,
+            blocks: block_data,
+        };
+        let encoded = bincode::serialize(&index).unwrap();
+        let compressed = zstd::encode_all(&encoded[..], 0).unwrap();
+        std::fs::write(&path, compressed).unwrap();
+
+        match load(&path) {
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+            Ok(_) => panic!("a version mismatch should be rejected, not silently misparsed"),
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
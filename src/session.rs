@@ -0,0 +1,113 @@
+//! Background, signal-aware mounting.
+//!
+//! `fuser::mount2` blocks the calling thread until the filesystem is
+//! unmounted, which makes it impossible to embed `SimpleFs` as a library or
+//! drive it from integration tests. This module mounts on a background
+//! thread instead and hands back a guard that unmounts on `Drop`.
+
+use std::io;
+use std::path::Path;
+use std::sync::mpsc;
+
+use fuser::{BackgroundSession, Filesystem, MountOption};
+use log::info;
+
+/// Mount options shared by every entry point in this module.
+pub fn mount_options() -> Vec<MountOption> {
+    vec![
+        MountOption::RW,
+        MountOption::FSName("simplefs".to_string()),
+        MountOption::AutoUnmount,
+        MountOption::DefaultPermissions,
+    ]
+}
+
+/// Mounts `fs` at `mountpoint` on a background thread.
+///
+/// The returned [`BackgroundSession`] unmounts the filesystem when dropped,
+/// so callers (including tests) can `drop` it for a clean shutdown instead
+/// of leaking the mountpoint. Generic over the [`Filesystem`] implementation
+/// so both `SimpleFs` and `backing::BackingFs` can be mounted this way.
+pub fn spawn<FS>(fs: FS, mountpoint: &Path) -> io::Result<BackgroundSession>
+where
+    FS: Filesystem + Send + 'static,
+{
+    fuser::spawn_mount2(fs, mountpoint, &mount_options())
+}
+
+/// Blocks until a `SIGINT` is received.
+///
+/// Used by `main` to keep a background-mounted session alive until the
+/// process is asked to shut down, at which point the caller drops the
+/// session to trigger an orderly unmount. Only `SIGINT` is caught: the
+/// `ctrlc` dependency is built without its `termination` feature, so a
+/// `SIGTERM` ends the process without this function returning.
+pub fn wait_for_shutdown_signal() {
+    let (tx, rx) = mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = tx.send(());
+    })
+    .expect("failed to install SIGINT handler");
+
+    let _ = rx.recv();
+    info!("Shutdown signal received");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+    use std::time::Duration;
+
+    /// A filesystem with no state, relying entirely on `Filesystem`'s
+    /// default (`ENOSYS`) method implementations.
+    struct NoopFs;
+    impl Filesystem for NoopFs {}
+
+    fn is_mounted(path: &Path) -> bool {
+        let dev = fs::metadata(path).unwrap().dev();
+        let parent_dev = fs::metadata(path.parent().unwrap()).unwrap().dev();
+        dev != parent_dev
+    }
+
+    /// `AutoUnmount` is only implemented via the `fusermount`/`fusermount3`
+    /// helper binary; skip on systems that don't have it installed rather
+    /// than failing a test that has nothing to do with the code under test.
+    fn has_fusermount() -> bool {
+        ["fusermount3", "fusermount"].iter().any(|bin| {
+            std::process::Command::new("which")
+                .arg(bin)
+                .output()
+                .is_ok_and(|out| out.status.success())
+        })
+    }
+
+    #[test]
+    fn drop_unmounts_the_session() {
+        if !has_fusermount() {
+            eprintln!("skipping: fusermount not installed");
+            return;
+        }
+
+        let mountpoint =
+            std::env::temp_dir().join(format!("simplefs-session-test-{}", std::process::id()));
+        fs::create_dir_all(&mountpoint).unwrap();
+
+        let session = spawn(NoopFs, &mountpoint).expect("failed to mount");
+        assert!(is_mounted(&mountpoint), "mountpoint should be live while the session is held");
+
+        drop(session);
+        // Unmounting happens on the background session's thread; give it a
+        // moment to finish before checking.
+        for _ in 0..50 {
+            if !is_mounted(&mountpoint) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(!is_mounted(&mountpoint), "mountpoint should be gone once the session is dropped");
+
+        fs::remove_dir(&mountpoint).unwrap();
+    }
+}
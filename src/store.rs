@@ -0,0 +1,175 @@
+//! Content-addressed, deduplicated block storage for file contents.
+//!
+//! Written data is split into fixed-size blocks, each hashed and kept once in
+//! a shared table keyed by hash. A `FileEntry` then holds an ordered list of
+//! hashes instead of owning its bytes directly, so files that share data (or
+//! the same data written twice) only pay for storage once.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Size of each content block. Data is split on this boundary before hashing.
+pub const BLOCK_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlockHash([u8; 32]);
+
+impl BlockHash {
+    fn of(data: &[u8]) -> Self {
+        let digest = Sha256::digest(data);
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(&digest);
+        BlockHash(bytes)
+    }
+}
+
+impl fmt::Debug for BlockHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in &self.0[..4] {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// A stored block's bytes alongside how many files currently reference it.
+type BlockEntry = (Arc<Vec<u8>>, usize);
+
+#[derive(Default)]
+pub struct BlockStore {
+    blocks: Mutex<HashMap<BlockHash, BlockEntry>>,
+}
+
+impl BlockStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Splits `data` into `BLOCK_SIZE` chunks, storing (or deduplicating
+    /// against) each one, and returns the ordered list of hashes that make up
+    /// the file.
+    pub fn store(&self, data: &[u8]) -> Vec<BlockHash> {
+        let mut blocks = self.blocks.lock().unwrap();
+        data.chunks(BLOCK_SIZE)
+            .map(|chunk| {
+                let hash = BlockHash::of(chunk);
+                blocks
+                    .entry(hash)
+                    .and_modify(|(_, refcount)| *refcount += 1)
+                    .or_insert_with(|| (Arc::new(chunk.to_vec()), 1));
+                hash
+            })
+            .collect()
+    }
+
+    /// Returns the raw bytes of a single stored block, if still live.
+    pub fn block(&self, hash: &BlockHash) -> Option<Arc<Vec<u8>>> {
+        self.blocks.lock().unwrap().get(hash).map(|(data, _)| data.clone())
+    }
+
+    /// Decrements the refcount of each hash in `hashes`, freeing any block
+    /// whose refcount reaches zero.
+    pub fn release(&self, hashes: &[BlockHash]) {
+        let mut blocks = self.blocks.lock().unwrap();
+        for hash in hashes {
+            if let Some((_, refcount)) = blocks.get_mut(hash) {
+                *refcount -= 1;
+                if *refcount == 0 {
+                    blocks.remove(hash);
+                }
+            }
+        }
+    }
+
+    /// Reassembles the `offset..offset+size` window of the file made up of
+    /// `hashes`, walking the block list rather than materializing the whole
+    /// file.
+    pub fn read_range(&self, hashes: &[BlockHash], offset: u64, size: u32) -> Vec<u8> {
+        let blocks = self.blocks.lock().unwrap();
+        let end = offset + size as u64;
+        let mut out = Vec::new();
+        let mut pos = 0u64;
+
+        for hash in hashes {
+            let Some((data, _)) = blocks.get(hash) else {
+                continue;
+            };
+            let block_start = pos;
+            let block_end = pos + data.len() as u64;
+            pos = block_end;
+
+            if block_end <= offset || block_start >= end {
+                continue;
+            }
+            let start_in_block = offset.saturating_sub(block_start) as usize;
+            let end_in_block = std::cmp::min(data.len() as u64, end - block_start) as usize;
+            out.extend_from_slice(&data[start_in_block..end_in_block]);
+        }
+
+        out
+    }
+
+    /// A read-only snapshot of every live block, for persistence.
+    pub fn snapshot(&self) -> HashMap<BlockHash, Vec<u8>> {
+        self.blocks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(hash, (data, _))| (*hash, (**data).clone()))
+            .collect()
+    }
+
+    /// Rebuilds a store from persisted block bytes, deriving each block's
+    /// refcount from how often its hash is referenced in `file_blocks`.
+    pub fn restore<'a>(
+        data: HashMap<BlockHash, Vec<u8>>,
+        file_blocks: impl Iterator<Item = &'a BlockHash>,
+    ) -> Self {
+        let mut refcounts: HashMap<BlockHash, usize> = HashMap::new();
+        for hash in file_blocks {
+            *refcounts.entry(*hash).or_insert(0) += 1;
+        }
+
+        let blocks = data
+            .into_iter()
+            .map(|(hash, bytes)| {
+                let refcount = refcounts.get(&hash).copied().unwrap_or(0);
+                (hash, (Arc::new(bytes), refcount))
+            })
+            .collect();
+
+        BlockStore {
+            blocks: Mutex::new(blocks),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dedups_identical_content_and_tracks_refcounts() {
+        let store = BlockStore::new();
+        let data = b"hello world".to_vec();
+
+        let a = store.store(&data);
+        let b = store.store(&data);
+        assert_eq!(a, b, "identical content should hash to the same blocks");
+        assert_eq!(store.snapshot().len(), 1, "content should be stored once");
+
+        store.release(&a);
+        assert_eq!(
+            store.read_range(&b, 0, data.len() as u32),
+            data,
+            "block should survive while b still references it"
+        );
+
+        store.release(&b);
+        assert!(store.snapshot().is_empty(), "block should be freed once every reference is released");
+    }
+}